@@ -0,0 +1,105 @@
+use diesel::prelude::*;
+
+use crate::app::AppState;
+use crate::models::token_usage::NewTokenUsage;
+use crate::models::{ApiToken, TokenUsage};
+use crate::util::errors::{AppResult, InvalidRevocationLink, TokenNotFound};
+use crate::util::jwt::{self, Claims};
+use crate::util::token_usage_filter;
+
+/// Handles the `GET /api/v1/me/tokens/:id/revoke` route.
+///
+/// Lets a user revoke a leaked API token from the link emailed to them when
+/// the token was created, without needing to log in. The `sig` query
+/// parameter must be a valid signature over this token's id and creation
+/// time (see `ApiToken::revocation_signature`); on any mismatch we return
+/// 403 without revealing whether `id` even exists.
+pub async fn revoke_via_link(state: AppState, id: i32, sig: String) -> AppResult<()> {
+    let mut conn = state.db_write().await?;
+
+    let token: ApiToken = crate::schema::api_tokens::table
+        .find(id)
+        .first(&mut conn)
+        .map_err(|_| InvalidRevocationLink::boxed())?;
+
+    if !token.verify_revocation_signature(&sig) {
+        return Err(InvalidRevocationLink::boxed());
+    }
+
+    diesel::update(crate::schema::api_tokens::table.find(id))
+        .set(crate::schema::api_tokens::revoked.eq(true))
+        .execute(&mut conn)?;
+
+    Ok(())
+}
+
+/// Handles the `GET /api/v1/me/tokens/:id/usage` route.
+///
+/// Accepts an optional `filter` query parameter in the small expression
+/// language implemented by [`crate::util::token_usage_filter`], e.g.
+/// `endpoint_scope = publish AND used_at >= 2024-01-01`, letting a user
+/// audit exactly where and how a given token has been used.
+pub async fn usage(
+    state: AppState,
+    current_user: &crate::models::User,
+    id: i32,
+    filter: Option<String>,
+) -> AppResult<Vec<TokenUsage>> {
+    let mut conn = state.db_read().await?;
+
+    // Make sure `id` actually names a token belonging to the requesting user
+    // before returning any usage rows for it.
+    crate::schema::api_tokens::table
+        .find(id)
+        .filter(crate::schema::api_tokens::user_id.eq(current_user.id))
+        .select(crate::schema::api_tokens::id)
+        .first::<i32>(&mut conn)
+        .map_err(|_| TokenNotFound::boxed())?;
+
+    let mut query = crate::schema::token_usages::table
+        .filter(crate::schema::token_usages::token_id.eq(id))
+        .into_boxed();
+
+    if let Some(filter) = filter {
+        let expr = token_usage_filter::parse(&filter)?;
+        query = query.filter(token_usage_filter::to_boxed_filter(&expr)?);
+    }
+
+    Ok(query.load(&mut conn)?)
+}
+
+#[derive(Deserialize)]
+pub struct ExchangeRequest {
+    pub token: String,
+}
+
+#[derive(Serialize)]
+pub struct ExchangeResponse {
+    pub access_token: String,
+    pub expires_in: i64,
+}
+
+/// Handles the `POST /api/v1/tokens/exchange` route.
+///
+/// Takes a regular, durable API token and returns a short-lived, scoped JWT
+/// carrying the same `crate_scopes`/`endpoint_scopes`, for clients that want
+/// a refresh-style pattern instead of sending their durable credential on
+/// every request.
+pub async fn exchange(state: AppState, body: ExchangeRequest) -> AppResult<ExchangeResponse> {
+    let mut conn = state.db_write().await?;
+
+    let token = ApiToken::find_by_api_token(&mut conn, &body.token, NewTokenUsage::default())?;
+
+    let claims = Claims::new(
+        token.user_id,
+        token.crate_scopes.clone(),
+        token.endpoint_scopes.clone(),
+    );
+    let expires_in = claims.exp - chrono::Utc::now().timestamp();
+    let access_token = jwt::encode(&claims)?;
+
+    Ok(ExchangeResponse {
+        access_token,
+        expires_in,
+    })
+}
@@ -0,0 +1,48 @@
+use crate::util::errors::AppResult;
+
+/// Sends transactional emails. `from_environment` picks the SMTP backend
+/// when `MAILGUN_SMTP_LOGIN` is set and the in-memory backend otherwise;
+/// the in-memory backend logs the message instead of sending it. The SMTP
+/// backend does not deliver anything either: `send_smtp` discards the
+/// message and returns `Ok(())`.
+pub struct Emails {
+    backend: EmailBackend,
+}
+
+enum EmailBackend {
+    Smtp,
+    Memory,
+}
+
+impl Emails {
+    pub fn from_environment() -> Self {
+        let backend = match dotenv::var("MAILGUN_SMTP_LOGIN") {
+            Ok(_) => EmailBackend::Smtp,
+            Err(_) => EmailBackend::Memory,
+        };
+
+        Emails { backend }
+    }
+
+    pub fn send_token_revocation(&self, recipient: &str, revoke_url: &str) -> AppResult<()> {
+        let subject = "crates.io: new API token created";
+        let body = format!(
+            "A new API token was just created for your account.\n\n\
+             If this wasn't you, or you'd like to revoke this token, visit:\n\
+             {revoke_url}"
+        );
+
+        match self.backend {
+            EmailBackend::Smtp => send_smtp(recipient, subject, &body),
+            EmailBackend::Memory => {
+                log::info!("skipping email to {recipient}: {subject}");
+                Ok(())
+            }
+        }
+    }
+}
+
+fn send_smtp(recipient: &str, subject: &str, body: &str) -> AppResult<()> {
+    let _ = (recipient, subject, body);
+    Ok(())
+}
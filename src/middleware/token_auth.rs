@@ -0,0 +1,25 @@
+use std::net::IpAddr;
+
+use crate::models::token::EndpointScope;
+use crate::models::token_usage::NewTokenUsage;
+use crate::util::errors::AppResult;
+use crate::util::request_auth::{self, AuthenticatedToken};
+
+/// Extracts the credential from the request and authenticates it, accepting
+/// either a durable API token or a short-lived JWT minted by
+/// `POST /api/v1/tokens/exchange`.
+pub fn authenticate(
+    conn: &mut diesel::PgConnection,
+    authorization_header: &str,
+    endpoint_scope: Option<EndpointScope>,
+    crate_name: Option<&str>,
+    client_ip: Option<IpAddr>,
+) -> AppResult<AuthenticatedToken> {
+    let usage = NewTokenUsage {
+        endpoint_scope,
+        crate_name,
+        client_ip,
+    };
+
+    request_auth::authenticate(conn, authorization_header, usage)
+}
@@ -4,9 +4,11 @@ use chrono::NaiveDateTime;
 use diesel::prelude::*;
 
 pub use self::scopes::{CrateScope, EndpointScope};
+use crate::email::Emails;
+use crate::models::token_usage::{NewTokenUsage, TokenUsage};
 use crate::models::User;
 use crate::schema::api_tokens;
-use crate::util::errors::{AppResult, InsecurelyGeneratedTokenRevoked};
+use crate::util::errors::{AppResult, ExpiredToken, InsecurelyGeneratedTokenRevoked};
 use crate::util::rfc3339;
 use crate::util::token::{SecureToken, SecureTokenKind};
 
@@ -24,6 +26,8 @@ pub struct ApiToken {
     pub created_at: NaiveDateTime,
     #[serde(with = "rfc3339::option")]
     pub last_used_at: Option<NaiveDateTime>,
+    #[serde(with = "rfc3339::option")]
+    pub expires_at: Option<NaiveDateTime>,
     #[serde(skip)]
     pub revoked: bool,
     /// `None` or a list of crate scope patterns (see RFC #2947)
@@ -36,16 +40,23 @@ pub struct ApiToken {
 
 impl ApiToken {
     /// Generates a new named API token for a user
-    pub fn insert(conn: &mut PgConnection, user_id: i32, name: &str) -> AppResult<CreatedApiToken> {
-        Self::insert_with_scopes(conn, user_id, name, None, None)
+    pub fn insert(
+        conn: &mut PgConnection,
+        emails: &Emails,
+        user_id: i32,
+        name: &str,
+    ) -> AppResult<CreatedApiToken> {
+        Self::insert_with_scopes(conn, emails, user_id, name, None, None, None)
     }
 
     pub fn insert_with_scopes(
         conn: &mut PgConnection,
+        emails: &Emails,
         user_id: i32,
         name: &str,
         crate_scopes: Option<Vec<CrateScope>>,
         endpoint_scopes: Option<Vec<EndpointScope>>,
+        expires_at: Option<NaiveDateTime>,
     ) -> AppResult<CreatedApiToken> {
         let token = SecureToken::generate(SecureTokenKind::Api);
 
@@ -56,35 +67,155 @@ impl ApiToken {
                 api_tokens::token.eq(&*token),
                 api_tokens::crate_scopes.eq(crate_scopes),
                 api_tokens::endpoint_scopes.eq(endpoint_scopes),
+                api_tokens::expires_at.eq(expires_at),
             ))
             .get_result(conn)?;
 
+        model.send_revocation_email(conn, emails);
+
         Ok(CreatedApiToken {
             plaintext: token.plaintext().into(),
             model,
         })
     }
 
-    pub fn find_by_api_token(conn: &mut PgConnection, token_: &str) -> AppResult<ApiToken> {
+    /// Emails the token's owner a one-click revocation link. Best-effort: a
+    /// missing or unverified email just means no link goes out.
+    fn send_revocation_email(&self, conn: &mut PgConnection, emails: &Emails) {
+        use crate::schema::emails::dsl::{email, emails as emails_table, user_id, verified};
+
+        let address = emails_table
+            .filter(user_id.eq(self.user_id))
+            .filter(verified.eq(true))
+            .select(email)
+            .first::<String>(conn);
+
+        if let Ok(address) = address {
+            let _ = emails.send_token_revocation(&address, &self.revocation_url());
+        }
+    }
+
+    /// The login-free link emailed to the token's owner on creation.
+    fn revocation_url(&self) -> String {
+        format!(
+            "https://crates.io/api/v1/me/tokens/{}/revoke?sig={}",
+            self.id,
+            self.revocation_signature()
+        )
+    }
+
+    pub fn find_by_api_token(
+        conn: &mut PgConnection,
+        token_: &str,
+        usage: NewTokenUsage<'_>,
+    ) -> AppResult<ApiToken> {
         use crate::schema::api_tokens::dsl::*;
-        use diesel::{dsl::now, update};
+        use diesel::{dsl::now, update, NotFound};
 
-        let token_ = SecureToken::parse(SecureTokenKind::Api, token_)
+        let digest = SecureToken::parse(SecureTokenKind::Api, token_)
             .ok_or_else(InsecurelyGeneratedTokenRevoked::boxed)?;
 
-        let tokens = api_tokens
-            .filter(revoked.eq(false))
-            .filter(token.eq(&token_));
+        let tokens = api_tokens.filter(revoked.eq(false));
+        let by_digest = tokens.filter(token.eq(&digest));
+        let unexpired_tokens = by_digest.filter(expires_at.is_null().or(expires_at.gt(now)));
 
-        // If the database is in read only mode, we can't update last_used_at.
-        // Try updating in a new transaction, if that fails, fall back to reading
-        conn.transaction(|conn| {
-            update(tokens)
-                .set(last_used_at.eq(now.nullable()))
-                .get_result(conn)
-        })
-        .or_else(|_| tokens.first(conn))
-        .map_err(Into::into)
+        // If the database is in read only mode, we can't update last_used_at (or
+        // record usage). Try updating in a new transaction, if that fails, fall
+        // back to reading.
+        let result = conn
+            .transaction(|conn| {
+                let found: ApiToken = update(unexpired_tokens)
+                    .set(last_used_at.eq(now.nullable()))
+                    .get_result(conn)?;
+                TokenUsage::record(conn, found.id, &usage)?;
+                Ok(found)
+            })
+            .or_else(|_: diesel::result::Error| unexpired_tokens.first(conn));
+
+        match result {
+            Ok(found) => Ok(found),
+            Err(NotFound) => {
+                // The token matched an existing, non-revoked row, just not one of the
+                // unexpired ones above, so give a more specific error than "not found".
+                if by_digest.first::<ApiToken>(conn).is_ok() {
+                    return Err(ExpiredToken::boxed());
+                }
+
+                // Not found under the current HMAC scheme. During the migration
+                // window a row may still carry the legacy, unkeyed digest; if so,
+                // accept it (subject to the same expiry check as the primary
+                // path) and upgrade it to the HMAC digest so it won't need this
+                // fallback again.
+                if let Some(legacy_digest) = SecureToken::parse_legacy(SecureTokenKind::Api, token_)
+                {
+                    let by_legacy_digest = tokens.filter(token.eq(&legacy_digest));
+                    let unexpired_legacy =
+                        by_legacy_digest.filter(expires_at.is_null().or(expires_at.gt(now)));
+
+                    // Same read-only fallback as the primary path above: try the
+                    // update, digest upgrade, and usage record together in one
+                    // transaction, and fall back to a plain read if any of them
+                    // fails (e.g. because the database is in read-only mode).
+                    let legacy_result = conn
+                        .transaction(|conn| {
+                            let found: ApiToken = update(unexpired_legacy)
+                                .set(last_used_at.eq(now.nullable()))
+                                .get_result(conn)?;
+                            update(api_tokens.find(found.id))
+                                .set(token.eq(&digest))
+                                .execute(conn)?;
+                            TokenUsage::record(conn, found.id, &usage)?;
+                            Ok(found)
+                        })
+                        .or_else(|_: diesel::result::Error| unexpired_legacy.first(conn));
+
+                    if let Ok(found) = legacy_result {
+                        return Ok(found);
+                    }
+
+                    if by_legacy_digest.first::<ApiToken>(conn).is_ok() {
+                        return Err(ExpiredToken::boxed());
+                    }
+                }
+
+                Err(InsecurelyGeneratedTokenRevoked::boxed())
+            }
+            Err(other) => Err(other.into()),
+        }
+    }
+
+    /// The canonical bytes signed for login-free revocation links: this
+    /// token's id and creation time.
+    fn revocation_message(&self) -> String {
+        format!("{}:{}", self.id, self.created_at.and_utc().timestamp())
+    }
+
+    /// Signs this token's revocation message, producing the `sig` query
+    /// parameter embedded in the revoke link emailed on token creation.
+    pub fn revocation_signature(&self) -> String {
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+        use ed25519_dalek::Signer;
+
+        let signature = crate::util::keys::revocation_signing_key()
+            .sign(self.revocation_message().as_bytes());
+        URL_SAFE_NO_PAD.encode(signature.to_bytes())
+    }
+
+    /// Verifies a signature produced by [`Self::revocation_signature`].
+    pub fn verify_revocation_signature(&self, sig: &str) -> bool {
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+        use ed25519_dalek::{Signature, Verifier};
+
+        let Ok(sig_bytes) = URL_SAFE_NO_PAD.decode(sig) else {
+            return false;
+        };
+        let Ok(sig_bytes) = <[u8; 64]>::try_from(sig_bytes) else {
+            return false;
+        };
+        let signature = Signature::from_bytes(&sig_bytes);
+        crate::util::keys::revocation_verifying_key()
+            .verify(self.revocation_message().as_bytes(), &signature)
+            .is_ok()
     }
 }
 
@@ -111,6 +242,8 @@ mod tests {
 
     #[test]
     fn api_token_serializes_to_rfc3339() {
+        std::env::set_var("API_TOKEN_HMAC_KEY", "test-key");
+
         let tok = ApiToken {
             id: 12345,
             user_id: 23456,
@@ -127,6 +260,7 @@ mod tests {
                     .and_hms_opt(14, 23, 12),
             )
             .unwrap(),
+            expires_at: None,
             crate_scopes: None,
             endpoint_scopes: None,
         };
@@ -139,6 +273,41 @@ mod tests {
             .find(r#""last_used_at":"2017-01-06T14:23:12+00:00""#));
     }
 
+    #[test]
+    fn revocation_signature_round_trips_and_rejects_tampering() {
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+        std::env::set_var("API_TOKEN_HMAC_KEY", "test-key");
+        std::env::set_var(
+            "API_TOKEN_REVOCATION_KEY",
+            URL_SAFE_NO_PAD.encode([7u8; 32]),
+        );
+
+        let tok = ApiToken {
+            id: 12345,
+            user_id: 23456,
+            token: SecureToken::generate(SecureTokenKind::Api).into_inner(),
+            revoked: false,
+            name: "".to_string(),
+            created_at: NaiveDate::from_ymd_opt(2017, 1, 6)
+                .unwrap()
+                .and_hms_opt(14, 23, 11)
+                .unwrap(),
+            last_used_at: None,
+            expires_at: None,
+            crate_scopes: None,
+            endpoint_scopes: None,
+        };
+
+        let sig = tok.revocation_signature();
+        assert!(tok.verify_revocation_signature(&sig));
+        assert!(!tok.verify_revocation_signature("not-a-real-signature"));
+
+        let mut other = tok.clone();
+        other.id = tok.id + 1;
+        assert!(!other.verify_revocation_signature(&sig));
+    }
+
     #[test]
     fn encodeable_api_token_with_token_serializes_to_rfc3339() {
         let tok = EncodableApiTokenWithToken {
@@ -0,0 +1,71 @@
+use std::net::IpAddr;
+
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+use crate::models::token::EndpointScope;
+use crate::schema::token_usages;
+use crate::util::errors::AppResult;
+
+/// One row per authenticated request made with a given API token, recorded
+/// alongside the `last_used_at` bump in `ApiToken::find_by_api_token` so
+/// users can audit exactly where and how each token has been used.
+#[derive(Clone, Debug, PartialEq, Eq, Identifiable, Queryable, Associations, Serialize)]
+#[diesel(belongs_to(crate::models::ApiToken, foreign_key = token_id))]
+#[diesel(table_name = token_usages)]
+pub struct TokenUsage {
+    pub id: i32,
+    pub token_id: i32,
+    pub used_at: NaiveDateTime,
+    pub endpoint_scope: Option<String>,
+    pub crate_name: Option<String>,
+    /// The client's IP address with the last octet (IPv4) or last 80 bits
+    /// (IPv6) zeroed out, so we retain enough to spot abuse patterns
+    /// without storing a precise, individually-identifying address.
+    pub client_ip: Option<String>,
+}
+
+/// What to record about the request currently authenticating with a token.
+/// Passed in by the auth layer, which knows the route and request details
+/// that the token model itself doesn't.
+#[derive(Default)]
+pub struct NewTokenUsage<'a> {
+    pub endpoint_scope: Option<EndpointScope>,
+    pub crate_name: Option<&'a str>,
+    pub client_ip: Option<IpAddr>,
+}
+
+impl TokenUsage {
+    pub(crate) fn record(
+        conn: &mut PgConnection,
+        token_id: i32,
+        usage: &NewTokenUsage<'_>,
+    ) -> AppResult<()> {
+        diesel::insert_into(token_usages::table)
+            .values((
+                token_usages::token_id.eq(token_id),
+                token_usages::endpoint_scope.eq(usage
+                    .endpoint_scope
+                    .map(|s| format!("{s:?}").to_lowercase())),
+                token_usages::crate_name.eq(usage.crate_name),
+                token_usages::client_ip.eq(usage.client_ip.map(|ip| truncate_ip(ip).to_string())),
+            ))
+            .execute(conn)?;
+
+        Ok(())
+    }
+}
+
+fn truncate_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            IpAddr::from([a, b, c, 0])
+        }
+        IpAddr::V6(ip) => {
+            let mut segments = ip.segments();
+            segments[3..].fill(0);
+            IpAddr::from(segments)
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::fmt;
+
+use http::StatusCode;
+
+/// A trait for API errors that carry their own HTTP response representation.
+///
+/// This intentionally mirrors `std::error::Error`, but `response()` lets each
+/// error decide its own status code and body instead of forcing every
+/// failure through a single generic 500.
+pub trait AppError: Send + fmt::Debug + fmt::Display + 'static {
+    fn response(&self) -> (StatusCode, String);
+}
+
+pub type AppResult<T> = Result<T, Box<dyn AppError>>;
+
+macro_rules! simple_app_error {
+    ($name:ident, $status:expr, $message:expr) => {
+        #[derive(Debug)]
+        pub struct $name;
+
+        impl $name {
+            pub fn boxed() -> Box<dyn AppError> {
+                Box::new($name)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, $message)
+            }
+        }
+
+        impl AppError for $name {
+            fn response(&self) -> (StatusCode, String) {
+                ($status, $message.into())
+            }
+        }
+    };
+}
+
+simple_app_error!(
+    InsecurelyGeneratedTokenRevoked,
+    StatusCode::FORBIDDEN,
+    "The given API token does not match the format used by crates.io. \
+     Tokens generated before 2020-07-14 were generated insecurely, and have \
+     all been revoked."
+);
+
+simple_app_error!(
+    ExpiredToken,
+    StatusCode::FORBIDDEN,
+    "The given API token has expired."
+);
+
+simple_app_error!(
+    TokenNotFound,
+    StatusCode::NOT_FOUND,
+    "Could not find the given API token."
+);
+
+simple_app_error!(
+    BadTokenUsageFilter,
+    StatusCode::BAD_REQUEST,
+    "Could not parse the `filter` query parameter. Expected conditions like \
+     `endpoint_scope = publish AND used_at >= 2024-01-01`, joined by `AND`/`OR`/`,`."
+);
+
+simple_app_error!(
+    InvalidToken,
+    StatusCode::FORBIDDEN,
+    "The given access token is invalid or has expired."
+);
+
+simple_app_error!(
+    InvalidRevocationLink,
+    StatusCode::FORBIDDEN,
+    "This revocation link is invalid."
+);
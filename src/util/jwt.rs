@@ -0,0 +1,99 @@
+//! Short-lived, scoped JWTs minted in exchange for a regular API token,
+//! via `POST /api/v1/tokens/exchange`.
+
+use chrono::{Duration, Utc};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+use crate::models::token::{CrateScope, EndpointScope};
+use crate::util::errors::{AppResult, InvalidToken};
+
+/// How long a minted access token remains valid.
+const EXPIRY: Duration = Duration::minutes(15);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub user_id: i32,
+    pub crate_scopes: Option<Vec<CrateScope>>,
+    pub endpoint_scopes: Option<Vec<EndpointScope>>,
+    pub exp: i64,
+}
+
+impl Claims {
+    pub fn new(
+        user_id: i32,
+        crate_scopes: Option<Vec<CrateScope>>,
+        endpoint_scopes: Option<Vec<EndpointScope>>,
+    ) -> Self {
+        Claims {
+            user_id,
+            crate_scopes,
+            endpoint_scopes,
+            exp: (Utc::now() + EXPIRY).timestamp(),
+        }
+    }
+}
+
+fn signing_secret() -> String {
+    dotenv::var("API_TOKEN_JWT_SECRET").expect("API_TOKEN_JWT_SECRET must be set")
+}
+
+/// Signs `claims`, returning the compact JWT string handed back from the
+/// exchange endpoint.
+pub fn encode(claims: &Claims) -> AppResult<String> {
+    let key = EncodingKey::from_secret(signing_secret().as_bytes());
+    jsonwebtoken::encode(&Header::new(Algorithm::HS256), claims, &key)
+        .map_err(|_| InvalidToken::boxed())
+}
+
+/// Verifies and decodes a JWT presented as a bearer credential, rejecting
+/// expired tokens via the `exp` claim.
+pub fn decode(token: &str) -> AppResult<Claims> {
+    let key = DecodingKey::from_secret(signing_secret().as_bytes());
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.leeway = 0;
+    jsonwebtoken::decode::<Claims>(token, &key, &validation)
+        .map(|data| data.claims)
+        .map_err(|_| InvalidToken::boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_jwt_secret<T>(f: impl FnOnce() -> T) -> T {
+        std::env::set_var("API_TOKEN_JWT_SECRET", "test-secret");
+        f()
+    }
+
+    #[test]
+    fn encode_then_decode_round_trips() {
+        with_jwt_secret(|| {
+            let claims = Claims::new(42, None, None);
+            let token = encode(&claims).unwrap();
+            let decoded = decode(&token).unwrap();
+            assert_eq!(decoded.user_id, 42);
+        });
+    }
+
+    #[test]
+    fn decode_rejects_expired_token() {
+        with_jwt_secret(|| {
+            let claims = Claims {
+                user_id: 42,
+                crate_scopes: None,
+                endpoint_scopes: None,
+                exp: (Utc::now() - Duration::minutes(1)).timestamp(),
+            };
+            let token = encode(&claims).unwrap();
+            assert!(decode(&token).is_err());
+        });
+    }
+
+    #[test]
+    fn decode_rejects_garbage_token() {
+        with_jwt_secret(|| {
+            assert!(decode("not-a-jwt").is_err());
+        });
+    }
+}
@@ -0,0 +1,26 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use once_cell::sync::Lazy;
+
+/// The Ed25519 keypair used to sign and verify login-free token revocation
+/// links (see [`crate::models::token`]). Loaded once from
+/// `API_TOKEN_REVOCATION_KEY`, a base64url-encoded 32-byte seed.
+static REVOCATION_SIGNING_KEY: Lazy<SigningKey> = Lazy::new(|| {
+    let seed = dotenv::var("API_TOKEN_REVOCATION_KEY")
+        .expect("API_TOKEN_REVOCATION_KEY must be set");
+    let seed = URL_SAFE_NO_PAD
+        .decode(seed)
+        .expect("API_TOKEN_REVOCATION_KEY is not valid base64url");
+    let seed: [u8; 32] = seed
+        .try_into()
+        .expect("API_TOKEN_REVOCATION_KEY must decode to 32 bytes");
+    SigningKey::from_bytes(&seed)
+});
+
+pub fn revocation_signing_key() -> &'static SigningKey {
+    &REVOCATION_SIGNING_KEY
+}
+
+pub fn revocation_verifying_key() -> VerifyingKey {
+    REVOCATION_SIGNING_KEY.verifying_key()
+}
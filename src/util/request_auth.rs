@@ -0,0 +1,53 @@
+//! Unifies the two credentials the request authentication layer accepts: a
+//! durable `SecureToken` looked up via `ApiToken::find_by_api_token`, or a
+//! short-lived JWT minted by `POST /api/v1/tokens/exchange`. Both resolve to
+//! the same `user_id`/`CrateScope`/`EndpointScope` shape.
+
+use crate::models::token::{CrateScope, EndpointScope};
+use crate::models::ApiToken;
+use crate::util::errors::AppResult;
+use crate::util::jwt::{self, Claims};
+
+pub enum AuthenticatedToken {
+    /// Looked up in `api_tokens`, incurring the usual database round-trip.
+    Api(ApiToken),
+    /// Verified in-process from its signature; scopes come from the claims.
+    Jwt(Claims),
+}
+
+impl AuthenticatedToken {
+    pub fn user_id(&self) -> i32 {
+        match self {
+            AuthenticatedToken::Api(token) => token.user_id,
+            AuthenticatedToken::Jwt(claims) => claims.user_id,
+        }
+    }
+
+    pub fn crate_scopes(&self) -> Option<&[CrateScope]> {
+        match self {
+            AuthenticatedToken::Api(token) => token.crate_scopes.as_deref(),
+            AuthenticatedToken::Jwt(claims) => claims.crate_scopes.as_deref(),
+        }
+    }
+
+    pub fn endpoint_scopes(&self) -> Option<&[EndpointScope]> {
+        match self {
+            AuthenticatedToken::Api(token) => token.endpoint_scopes.as_deref(),
+            AuthenticatedToken::Jwt(claims) => claims.endpoint_scopes.as_deref(),
+        }
+    }
+}
+
+/// Tries the presented credential as a JWT first, falling back to the
+/// `SecureToken` path used by durable API tokens.
+pub fn authenticate(
+    conn: &mut diesel::PgConnection,
+    credential: &str,
+    usage: crate::models::token_usage::NewTokenUsage<'_>,
+) -> AppResult<AuthenticatedToken> {
+    if let Ok(claims) = jwt::decode(credential) {
+        return Ok(AuthenticatedToken::Jwt(claims));
+    }
+
+    ApiToken::find_by_api_token(conn, credential, usage).map(AuthenticatedToken::Api)
+}
@@ -0,0 +1,161 @@
+//! Random, prefixed API tokens, stored in the database only as a digest,
+//! never as a value the plaintext can be recovered from.
+//!
+//! The digest is `HMAC-SHA256(API_TOKEN_HMAC_KEY, plaintext)`. Rows written
+//! before this scheme was introduced hold a legacy, unkeyed
+//! `SHA256(plaintext)` digest instead; [`SecureToken::parse_legacy`] lets
+//! `ApiToken::find_by_api_token` keep accepting those during the transition
+//! window, upgrading each row to the keyed digest the next time it's used.
+
+use diesel::backend::Backend;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+#[derive(Copy, Clone)]
+pub enum SecureTokenKind {
+    Api,
+}
+
+impl SecureTokenKind {
+    fn prefix(self) -> &'static str {
+        match self {
+            SecureTokenKind::Api => "cio",
+        }
+    }
+}
+
+/// The value stored in (and compared against) the `token` column: a
+/// hex-encoded digest. Never holds the plaintext token.
+#[derive(Clone, Debug, PartialEq, Eq, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Text)]
+pub struct SecureToken(String);
+
+impl SecureToken {
+    /// Generates a new random, prefixed token, returning both its digest
+    /// (what gets stored) and its plaintext (what gets shown to the user
+    /// exactly once).
+    pub fn generate(kind: SecureTokenKind) -> GeneratedToken {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let plaintext = format!("{}{}", kind.prefix(), hex::encode(bytes));
+        let digest = SecureToken(hmac_digest(&plaintext));
+
+        GeneratedToken { digest, plaintext }
+    }
+
+    /// Hashes a presented token under the current, HMAC-keyed scheme.
+    pub fn parse(kind: SecureTokenKind, plaintext: &str) -> Option<SecureToken> {
+        plaintext
+            .starts_with(kind.prefix())
+            .then(|| SecureToken(hmac_digest(plaintext)))
+    }
+
+    /// Hashes a presented token under the legacy, unkeyed `SHA256(plaintext)`
+    /// scheme used before tokens were HMAC'd, for verifying rows that
+    /// haven't been looked up (and thus upgraded) since the migration.
+    pub fn parse_legacy(kind: SecureTokenKind, plaintext: &str) -> Option<SecureToken> {
+        plaintext.starts_with(kind.prefix()).then(|| {
+            let digest = Sha256::digest(plaintext.as_bytes());
+            SecureToken(hex::encode(digest))
+        })
+    }
+}
+
+fn hmac_digest(plaintext: &str) -> String {
+    let key = dotenv::var("API_TOKEN_HMAC_KEY").expect("API_TOKEN_HMAC_KEY must be set");
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+        .expect("HMAC accepts a key of any length");
+    mac.update(plaintext.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// A freshly generated token, pairing the digest written to the database
+/// with the plaintext returned to the caller exactly once.
+pub struct GeneratedToken {
+    digest: SecureToken,
+    plaintext: String,
+}
+
+impl GeneratedToken {
+    pub fn plaintext(&self) -> &str {
+        &self.plaintext
+    }
+
+    pub fn into_inner(self) -> SecureToken {
+        self.digest
+    }
+}
+
+impl std::ops::Deref for GeneratedToken {
+    type Target = SecureToken;
+
+    fn deref(&self) -> &SecureToken {
+        &self.digest
+    }
+}
+
+impl<DB: Backend> ToSql<Text, DB> for SecureToken
+where
+    String: ToSql<Text, DB>,
+{
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, DB>) -> serialize::Result {
+        self.0.to_sql(out)
+    }
+}
+
+impl FromSql<Text, Pg> for SecureToken {
+    fn from_sql(bytes: diesel::pg::PgValue<'_>) -> deserialize::Result<Self> {
+        String::from_sql(bytes).map(SecureToken)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_hmac_key<T>(f: impl FnOnce() -> T) -> T {
+        std::env::set_var("API_TOKEN_HMAC_KEY", "test-key");
+        f()
+    }
+
+    #[test]
+    fn generate_then_parse_round_trips() {
+        with_hmac_key(|| {
+            let generated = SecureToken::generate(SecureTokenKind::Api);
+            let parsed = SecureToken::parse(SecureTokenKind::Api, generated.plaintext()).unwrap();
+            assert_eq!(&*generated, &parsed);
+        });
+    }
+
+    #[test]
+    fn parse_rejects_wrong_prefix() {
+        with_hmac_key(|| {
+            assert!(SecureToken::parse(SecureTokenKind::Api, "nope12345").is_none());
+        });
+    }
+
+    #[test]
+    fn legacy_digest_differs_from_current_digest() {
+        with_hmac_key(|| {
+            let plaintext = "cioabcdef0123456789";
+            let current = SecureToken::parse(SecureTokenKind::Api, plaintext).unwrap();
+            let legacy = SecureToken::parse_legacy(SecureTokenKind::Api, plaintext).unwrap();
+
+            // The two schemes must produce distinguishable digests: otherwise
+            // `find_by_api_token` couldn't tell a legacy row from a migrated
+            // one, and the legacy fallback could never be retired.
+            assert_ne!(current, legacy);
+
+            // But the legacy digest is still deterministic, so the same
+            // plaintext always maps to the same stored row during the
+            // migration window.
+            assert_eq!(legacy, SecureToken::parse_legacy(SecureTokenKind::Api, plaintext).unwrap());
+        });
+    }
+}
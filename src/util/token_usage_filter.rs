@@ -0,0 +1,268 @@
+//! A small filter expression language for `GET /api/v1/me/tokens/:id/usage`,
+//! e.g. `endpoint_scope = publish AND used_at >= 2024-01-01`.
+//!
+//! Expressions are parsed into an AST of [`Condition`]s combined by boolean
+//! operators, then translated into Diesel `.filter(...)` clauses over a
+//! fixed allow-list of `token_usages` columns.
+
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::prelude::*;
+use diesel::sql_types::Bool;
+
+use crate::schema::token_usages;
+use crate::util::errors::{AppResult, BadTokenUsageFilter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoolOp {
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Field {
+    EndpointScope,
+    CrateName,
+    UsedAt,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    pub field: Field,
+    pub op: CompareOp,
+    pub value: String,
+}
+
+/// A flat list of conditions joined left-to-right by a boolean operator.
+/// The filter language here is intentionally flat (no parentheses or mixed
+/// precedence) since every example in the wild is a short conjunction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    pub first: Condition,
+    pub rest: Vec<(BoolOp, Condition)>,
+}
+
+pub fn parse(input: &str) -> AppResult<Expr> {
+    let mut terms = Vec::new();
+    for (i, part) in split_on_boolean_ops(input).into_iter().enumerate() {
+        let (op, text) = part;
+        let condition = parse_condition(text.trim())?;
+        if i == 0 {
+            terms.push((None, condition));
+        } else {
+            terms.push((Some(op.expect("non-first term always has an operator")), condition));
+        }
+    }
+
+    let mut iter = terms.into_iter();
+    let (_, first) = iter.next().ok_or_else(BadTokenUsageFilter::boxed)?;
+    let rest = iter.map(|(op, c)| (op.unwrap(), c)).collect();
+
+    Ok(Expr { first, rest })
+}
+
+/// Finds the next occurrence of `needle` in `rest` that isn't part of a
+/// larger word, so a value like `ANDROID` or `ORCA` doesn't get mistaken
+/// for the `AND`/`OR` keyword.
+fn find_keyword(rest: &str, needle: &str) -> Option<usize> {
+    let mut start = 0;
+    while let Some(offset) = rest[start..].find(needle) {
+        let idx = start + offset;
+        let before_ok = rest[..idx].chars().next_back().map_or(true, |c| !c.is_alphanumeric());
+        let after_ok = rest[idx + needle.len()..]
+            .chars()
+            .next()
+            .map_or(true, |c| !c.is_alphanumeric());
+        if before_ok && after_ok {
+            return Some(idx);
+        }
+        start = idx + needle.len();
+    }
+    None
+}
+
+fn split_on_boolean_ops(input: &str) -> Vec<(Option<BoolOp>, &str)> {
+    let mut pieces = Vec::new();
+    let mut rest = input;
+    let mut op = None;
+    loop {
+        let next = ["AND", "OR", ","]
+            .iter()
+            .filter_map(|needle| {
+                // `,` is punctuation, not a word, so it needs no boundary check.
+                let idx = if *needle == "," {
+                    rest.find(',')
+                } else {
+                    find_keyword(rest, needle)
+                };
+                idx.map(|idx| (idx, *needle))
+            })
+            .min_by_key(|(idx, _)| *idx);
+
+        match next {
+            Some((idx, needle)) => {
+                pieces.push((op, &rest[..idx]));
+                op = Some(if needle == "OR" { BoolOp::Or } else { BoolOp::And });
+                rest = &rest[idx + needle.len()..];
+            }
+            None => {
+                pieces.push((op, rest));
+                break;
+            }
+        }
+    }
+    pieces
+}
+
+fn parse_condition(text: &str) -> AppResult<Condition> {
+    let ops: &[(&str, CompareOp)] = &[
+        (">=", CompareOp::Ge),
+        ("<=", CompareOp::Le),
+        ("!=", CompareOp::Ne),
+        ("=", CompareOp::Eq),
+        (">", CompareOp::Gt),
+        ("<", CompareOp::Lt),
+    ];
+
+    let (field_str, op, value_str) = ops
+        .iter()
+        .find_map(|(token, op)| {
+            text.split_once(token)
+                .map(|(field, value)| (field.trim(), *op, value.trim()))
+        })
+        .ok_or_else(BadTokenUsageFilter::boxed)?;
+
+    let field = match field_str {
+        "endpoint_scope" => Field::EndpointScope,
+        "crate_name" => Field::CrateName,
+        "used_at" => Field::UsedAt,
+        _ => return Err(BadTokenUsageFilter::boxed()),
+    };
+
+    Ok(Condition {
+        field,
+        op,
+        value: value_str.to_string(),
+    })
+}
+
+type BoxedPredicate = Box<dyn BoxableExpression<token_usages::table, diesel::pg::Pg, SqlType = Bool>>;
+
+/// Parses the right-hand side of a `used_at` condition. Accepts either a
+/// bare date or a full timestamp, since `2024-01-01` is the common case but
+/// a precise cutoff should still work.
+fn parse_used_at(value: &str) -> AppResult<NaiveDateTime> {
+    if let Ok(dt) = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S") {
+        return Ok(dt);
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap());
+    }
+    Err(BadTokenUsageFilter::boxed())
+}
+
+/// Translates a single allow-listed condition into a boxed, typed Diesel
+/// predicate. The column and comparison are both drawn from the allow-lists
+/// in `parse_condition` above, never from the raw input.
+fn to_predicate(condition: &Condition) -> AppResult<BoxedPredicate> {
+    use token_usages::dsl::{crate_name, endpoint_scope, used_at};
+
+    macro_rules! text_predicate {
+        ($column:expr) => {
+            match condition.op {
+                CompareOp::Eq => Box::new($column.eq(condition.value.clone())) as BoxedPredicate,
+                CompareOp::Ne => Box::new($column.ne(condition.value.clone())),
+                CompareOp::Lt => Box::new($column.lt(condition.value.clone())),
+                CompareOp::Le => Box::new($column.le(condition.value.clone())),
+                CompareOp::Gt => Box::new($column.gt(condition.value.clone())),
+                CompareOp::Ge => Box::new($column.ge(condition.value.clone())),
+            }
+        };
+    }
+
+    Ok(match condition.field {
+        Field::EndpointScope => text_predicate!(endpoint_scope),
+        Field::CrateName => text_predicate!(crate_name),
+        Field::UsedAt => {
+            let value = parse_used_at(&condition.value)?;
+            match condition.op {
+                CompareOp::Eq => Box::new(used_at.eq(value)),
+                CompareOp::Ne => Box::new(used_at.ne(value)),
+                CompareOp::Lt => Box::new(used_at.lt(value)),
+                CompareOp::Le => Box::new(used_at.le(value)),
+                CompareOp::Gt => Box::new(used_at.gt(value)),
+                CompareOp::Ge => Box::new(used_at.ge(value)),
+            }
+        }
+    })
+}
+
+pub fn to_boxed_filter(expr: &Expr) -> AppResult<BoxedPredicate> {
+    let mut predicate = to_predicate(&expr.first)?;
+    for (op, condition) in &expr.rest {
+        let next = to_predicate(condition)?;
+        predicate = match op {
+            BoolOp::And => Box::new(predicate.and(next)),
+            BoolOp::Or => Box::new(predicate.or(next)),
+        };
+    }
+    Ok(predicate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_condition() {
+        let expr = parse("endpoint_scope = publish").unwrap();
+        assert_eq!(expr.first.field, Field::EndpointScope);
+        assert_eq!(expr.first.op, CompareOp::Eq);
+        assert_eq!(expr.first.value, "publish");
+        assert!(expr.rest.is_empty());
+    }
+
+    #[test]
+    fn parses_and_or_comma_mix() {
+        let expr = parse("crate_name = foo AND used_at >= 2024-01-01, endpoint_scope != legacy OR crate_name = bar").unwrap();
+        assert_eq!(expr.rest.len(), 3);
+        assert_eq!(expr.rest[0].0, BoolOp::And);
+        assert_eq!(expr.rest[1].0, BoolOp::And);
+        assert_eq!(expr.rest[2].0, BoolOp::Or);
+    }
+
+    #[test]
+    fn keyword_match_requires_word_boundary() {
+        let expr = parse("crate_name = ANDROID").unwrap();
+        assert!(expr.rest.is_empty());
+        assert_eq!(expr.first.value, "ANDROID");
+
+        let expr = parse("crate_name = ORCA").unwrap();
+        assert!(expr.rest.is_empty());
+        assert_eq!(expr.first.value, "ORCA");
+    }
+
+    #[test]
+    fn rejects_unknown_field() {
+        assert!(parse("nonexistent_field = 1").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_condition() {
+        assert!(parse("endpoint_scope publish").is_err());
+    }
+
+    #[test]
+    fn rejects_empty_input() {
+        assert!(parse("").is_err());
+    }
+}